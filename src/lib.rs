@@ -0,0 +1,496 @@
+//! Core FASTQ i5-index-rewriting logic, usable as a library by other
+//! bioinformatics tools as well as by the `fastq-fix-i5` binary.
+
+use std::io::{self, BufRead, Read, Write};
+use memchr::{memchr, memrchr};
+
+mod parallel;
+pub use parallel::transform_threaded;
+
+/// Return the complement of a DNA base (A,C,G,T,N), preserving case.
+#[inline(always)]
+pub fn complement_base(b: u8) -> u8 {
+    // Handles A,C,G,T,N (upper/lower). Leaves other bytes unchanged.
+    match b {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'N' => b'N',
+        b'a' => b't',
+        b'c' => b'g',
+        b'g' => b'c',
+        b't' => b'a',
+        b'n' => b'n',
+        _ => b,
+    }
+}
+
+#[inline(always)]
+fn reverse_complement_in_place(buf: &mut [u8]) {
+    let mut i = 0;
+    let mut j = buf.len();
+
+    while i < j {
+        j -= 1;
+        let a = complement_base(buf[i]);
+        let b = complement_base(buf[j]);
+        buf[i] = b;
+        buf[j] = a;
+        i += 1;
+    }
+}
+
+/// Which index field(s) in a FASTQ header to reverse-complement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevcompMode {
+    None,
+    I7,
+    I5,
+    Both,
+}
+
+/// Options controlling how [`rewrite_header_index`] (and the [`transform`]
+/// family built on it) locates and rewrites a header's index field(s).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// Which index field(s) to reverse-complement.
+    pub revcomp: RevcompMode,
+    /// Treat the whole post-`:` index as a single field (no `i7+i5` split),
+    /// as produced by single-index Illumina runs.
+    pub single_index: bool,
+}
+
+impl Default for Options {
+    /// Matches the tool's original behavior: dual-index, i5 reverse-complemented.
+    fn default() -> Self {
+        Options {
+            revcomp: RevcompMode::I5,
+            single_index: false,
+        }
+    }
+}
+
+/// Rewrite a FASTQ header line's index field(s) in place, expected to end
+/// with "...:i7+i5\n" (dual index) or "...:index\n" (single index, when
+/// `opts.single_index` is set), with or without a final newline.
+/// Returns false if no index field was found (no rewrite); true if rewritten.
+pub fn rewrite_header_index(header: &mut [u8], opts: Options) -> bool {
+    if header.is_empty() || header[0] != b'@' {
+        // Not a FASTQ header; pass through unchanged.
+        return false;
+    }
+
+    // Check for trailing newline.
+    let has_nl = header.last() == Some(&b'\n');
+    let end = if has_nl {
+        let without_nl = header.len() - 1;
+        if without_nl > 0 && header[without_nl - 1] == b'\r' {
+            without_nl - 1
+        } else {
+            without_nl
+        }
+    } else {
+        header.len()
+    };
+
+    // Find last ':' in the header; everything after it is the index field(s).
+    let Some(j) = memrchr(b':', &header[..end]) else {
+        return false;
+    };
+    let index_start = j + 1;
+
+    if opts.single_index {
+        if opts.revcomp == RevcompMode::None {
+            return false;
+        }
+        reverse_complement_in_place(&mut header[index_start..end]);
+        return true;
+    }
+
+    // Dual index: i7 and i5 are split by '+'.
+    let Some(rel_plus) = memchr(b'+', &header[index_start..end]) else {
+        return false;
+    };
+    let plus_pos = index_start + rel_plus;
+
+    let mut rewritten = false;
+    if matches!(opts.revcomp, RevcompMode::I7 | RevcompMode::Both) {
+        reverse_complement_in_place(&mut header[index_start..plus_pos]);
+        rewritten = true;
+    }
+    if matches!(opts.revcomp, RevcompMode::I5 | RevcompMode::Both) {
+        reverse_complement_in_place(&mut header[plus_pos + 1..end]);
+        rewritten = true;
+    }
+    rewritten
+}
+
+/// Rewrite a FASTQ header's i5 field only. Equivalent to
+/// [`rewrite_header_index`] with the default [`Options`].
+pub fn rewrite_header_i5(header: &mut [u8]) -> bool {
+    rewrite_header_index(header, Options::default())
+}
+
+/// Counts produced by a call to [`transform`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of FASTQ records read.
+    pub records: u64,
+    /// Number of records whose header was actually rewritten (i.e. for which
+    /// [`rewrite_header_index`] returned true under the given [`Options`]).
+    pub headers_rewritten: u64,
+}
+
+fn truncated_record_err() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "truncated FASTQ record (expected header, sequence, '+' separator and quality)",
+    )
+}
+
+/// Length of a line's content, excluding its `\n` or `\r\n` terminator.
+#[inline(always)]
+fn line_len_no_terminator(line: &[u8]) -> usize {
+    let n = line.len();
+    if n >= 2 && line[n - 2] == b'\r' && line[n - 1] == b'\n' {
+        n - 2
+    } else if n >= 1 && line[n - 1] == b'\n' {
+        n - 1
+    } else {
+        n
+    }
+}
+
+/// Read one line (including the trailing '\n' if present) into buf.
+/// Returns number of bytes read (0 on EOF).
+#[inline(always)]
+fn read_line<R: BufRead + ?Sized>(r: &mut R, buf: &mut Vec<u8>) -> io::Result<usize> {
+    buf.clear();
+    let mut total = 0usize;
+    loop {
+        let available = r.fill_buf()?;
+        if available.is_empty() {
+            return Ok(total);
+        }
+        if let Some(pos) = memchr(b'\n', available) {
+            // include newline
+            buf.extend_from_slice(&available[..=pos]);
+            let consume = pos + 1;
+            r.consume(consume);
+            total += consume;
+            return Ok(total);
+        } else {
+            // consume all
+            buf.extend_from_slice(available);
+            let consume = available.len();
+            r.consume(consume);
+            total += consume;
+        }
+    }
+}
+
+/// A single parsed FASTQ record. Every non-header line is kept exactly as
+/// read (including its terminator) so unusual input round-trips untouched.
+#[derive(Default)]
+pub(crate) struct Record {
+    pub(crate) header: Vec<u8>,
+    seq_lines: Vec<Vec<u8>>,
+    plus: Vec<u8>,
+    qual_lines: Vec<Vec<u8>>,
+}
+
+/// Read one FASTQ record from `input` into `rec`. Returns `Ok(false)` on a
+/// clean EOF before any bytes of a new record are read, `Ok(true)` once a
+/// full record has been parsed, or an error on a truncated record.
+pub(crate) fn read_record<R: BufRead + ?Sized>(input: &mut R, rec: &mut Record) -> io::Result<bool> {
+    rec.header.clear();
+    if read_line(input, &mut rec.header)? == 0 {
+        return Ok(false); // clean EOF
+    }
+
+    // Sequence lines may be wrapped across multiple lines; accumulate until
+    // we hit the '+' separator line.
+    rec.seq_lines.clear();
+    let mut seq_len = 0usize;
+    loop {
+        let mut line = Vec::new();
+        if read_line(input, &mut line)? == 0 {
+            return Err(truncated_record_err());
+        }
+        if line.first() == Some(&b'+') {
+            rec.plus = line;
+            break;
+        }
+        seq_len += line_len_no_terminator(&line);
+        rec.seq_lines.push(line);
+    }
+
+    // Quality may likewise be wrapped; read lines until their combined
+    // length (excluding terminators) matches the sequence length. Always
+    // read at least one quality line, even for a zero-length (empty)
+    // sequence, so its blank quality line is consumed here rather than
+    // left in the stream to desync the next record.
+    rec.qual_lines.clear();
+    let mut qual_len = 0usize;
+    loop {
+        let mut line = Vec::new();
+        if read_line(input, &mut line)? == 0 {
+            return Err(truncated_record_err());
+        }
+        qual_len += line_len_no_terminator(&line);
+        rec.qual_lines.push(line);
+        if qual_len >= seq_len {
+            break;
+        }
+    }
+
+    Ok(true)
+}
+
+pub(crate) fn write_record<W: Write + ?Sized>(writer: &mut W, rec: &Record) -> io::Result<()> {
+    writer.write_all(&rec.header)?;
+    for line in &rec.seq_lines {
+        writer.write_all(line)?;
+    }
+    writer.write_all(&rec.plus)?;
+    for line in &rec.qual_lines {
+        writer.write_all(line)?;
+    }
+    Ok(())
+}
+
+/// Stream FASTQ records from `reader` to `writer`, rewriting each record's
+/// index field(s) per `opts`. Handles CRLF line endings and sequence/quality
+/// wrapped across multiple lines. Returns the record and rewrite counts as
+/// [`Stats`].
+pub fn transform<R: Read, W: Write>(reader: R, mut writer: W, opts: Options) -> io::Result<Stats> {
+    let mut input = io::BufReader::new(reader);
+    let mut stats = Stats::default();
+    let mut rec = Record::default();
+
+    while read_record(&mut input, &mut rec)? {
+        stats.records += 1;
+        if rewrite_header_index(&mut rec.header, opts) {
+            stats.headers_rewritten += 1;
+        }
+        write_record(&mut writer, &rec)?;
+    }
+
+    Ok(stats)
+}
+
+/// Like [`transform`], but runs two FASTQ streams (R1 and R2 of a read pair)
+/// in lockstep, rewriting each mate's header independently per `opts`.
+/// Errors if the two inputs don't have the same number of records, since a
+/// mismatch would desynchronize the pair.
+pub fn transform_paired<R1: Read, R2: Read, W1: Write, W2: Write>(
+    reader1: R1,
+    reader2: R2,
+    mut writer1: W1,
+    mut writer2: W2,
+    opts: Options,
+) -> io::Result<(Stats, Stats)> {
+    let mut input1 = io::BufReader::new(reader1);
+    let mut input2 = io::BufReader::new(reader2);
+    let mut stats1 = Stats::default();
+    let mut stats2 = Stats::default();
+    let mut rec1 = Record::default();
+    let mut rec2 = Record::default();
+
+    loop {
+        let has1 = read_record(&mut input1, &mut rec1)?;
+        let has2 = read_record(&mut input2, &mut rec2)?;
+        match (has1, has2) {
+            (false, false) => break,
+            (true, true) => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "mismatched record count between R1 and R2 input",
+                ))
+            }
+        }
+
+        stats1.records += 1;
+        if rewrite_header_index(&mut rec1.header, opts) {
+            stats1.headers_rewritten += 1;
+        }
+        write_record(&mut writer1, &rec1)?;
+
+        stats2.records += 1;
+        if rewrite_header_index(&mut rec2.header, opts) {
+            stats2.headers_rewritten += 1;
+        }
+        write_record(&mut writer2, &rec2)?;
+    }
+
+    Ok((stats1, stats2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::actacttgag(
+        b"@VH00821:6:AACCCKLM5:1:1101:18231:1000 1:N:0:TCTTGAGGTT+ACTACTTGAG\n",
+        b"@VH00821:6:AACCCKLM5:1:1101:18231:1000 1:N:0:TCTTGAGGTT+CTCAAGTAGT\n"
+    )]
+    #[case::acgt(
+        b"@r1 1:N:0:AAAA+ACGT\n",
+        b"@r1 1:N:0:AAAA+ACGT\n"
+    )]
+    #[case::acgt_lowercase(
+        b"@r2 1:N:0:CCCC+acgt\n",
+        b"@r2 1:N:0:CCCC+acgt\n"
+    )]
+    #[case::nnnn(
+        b"@r3 1:N:0:GGGG+NNNN\n",
+        b"@r3 1:N:0:GGGG+NNNN\n"
+    )]
+    #[case::actg_mixedcase(
+        b"@r4 1:N:0:TTTT+AcTg\n",
+        b"@r4 1:N:0:TTTT+cAgT\n"
+    )]
+    #[case::extra_colons(
+        b"@inst:run:flow:lane:tile:x:y 1:N:0:AAAA+TTTT\n",
+        b"@inst:run:flow:lane:tile:x:y 1:N:0:AAAA+AAAA\n"
+    )]
+    #[case::no_plus(
+        b"@r5 1:N:0:AAAA\n",
+        b"@r5 1:N:0:AAAA\n"
+    )]
+    #[case::no_colon(
+        b"@r6 no_index_here\n",
+        b"@r6 no_index_here\n"
+    )]
+    #[case::no_newline(
+        b"@r7 1:N:0:CCCC+AGTC",
+        b"@r7 1:N:0:CCCC+GACT"
+    )]
+    #[case::crlf(
+        b"@r8 1:N:0:CCCC+AGTC\r\n",
+        b"@r8 1:N:0:CCCC+GACT\r\n"
+    )]
+    #[case::empty_header(
+        b"@\n",
+        b"@\n"
+    )]
+    #[case::empty_i5(
+        b"@pyt1 1:N:0:AAAA+\n",
+        b"@pyt1 1:N:0:AAAA+\n"
+    )]
+    #[case::single_a(
+        b"@pyt2 1:N:0:AAAA+A\n",
+        b"@pyt2 1:N:0:AAAA+T\n"
+    )]
+    #[case::single_n(
+        b"@pyt3 1:N:0:AAAA+N\n",
+        b"@pyt3 1:N:0:AAAA+N\n"
+    )]
+    #[case::mixed_case_short(
+        b"@pyt4 1:N:0:AAAA+AaCg\n",
+        b"@pyt4 1:N:0:AAAA+cGtT\n"
+    )]
+    #[case::acgtn(
+        b"@pyt5 1:N:0:AAAA+AcgTN\n",
+        b"@pyt5 1:N:0:AAAA+NAcgT\n"
+    )]
+    #[case::all_as(
+        b"@pyt6 1:N:0:AAAA+AAAA\n",
+        b"@pyt6 1:N:0:AAAA+TTTT\n"
+    )]
+    #[case::all_cs(
+        b"@pyt7 1:N:0:AAAA+CCCC\n",
+        b"@pyt7 1:N:0:AAAA+GGGG\n"
+    )]
+    #[case::at_repeat(
+        b"@pyt8 1:N:0:AAAA+ATATAT\n",
+        b"@pyt8 1:N:0:AAAA+ATATAT\n"
+    )]
+    #[case::cg_repeat(
+        b"@pyt9 1:N:0:AAAA+CGCGCG\n",
+        b"@pyt9 1:N:0:AAAA+CGCGCG\n"
+    )]
+    #[case::ns_flanking(
+        b"@pyt10 1:N:0:AAAA+NNACGTNN\n",
+        b"@pyt10 1:N:0:AAAA+NNACGTNN\n"
+    )]
+    #[case::general_atcacg(
+        b"@pyt11 1:N:0:AAAA+ATCACG\n",
+        b"@pyt11 1:N:0:AAAA+CGTGAT\n"
+    )]
+    #[case::general_ttaggc(
+        b"@pyt12 1:N:0:AAAA+TTAGGC\n",
+        b"@pyt12 1:N:0:AAAA+GCCTAA\n"
+    )]
+    fn rewrite_header_i5_cases(#[case] input: &[u8], #[case] expected: &[u8]) {
+        let mut header = input.to_vec();
+        rewrite_header_i5(&mut header);
+        assert_eq!(
+            header.as_slice(),
+            expected,
+            "input = {:?}",
+            std::str::from_utf8(input).unwrap_or("<non-utf8>")
+        );
+    }
+
+    #[rstest]
+    #[case::i7_only(
+        b"@r1 1:N:0:AAAA+TTTT\n",
+        RevcompMode::I7,
+        false,
+        b"@r1 1:N:0:TTTT+TTTT\n"
+    )]
+    #[case::both(
+        b"@r1 1:N:0:AAAA+TTTT\n",
+        RevcompMode::Both,
+        false,
+        b"@r1 1:N:0:TTTT+AAAA\n"
+    )]
+    #[case::none(
+        b"@r1 1:N:0:AAAA+TTTT\n",
+        RevcompMode::None,
+        false,
+        b"@r1 1:N:0:AAAA+TTTT\n"
+    )]
+    #[case::single_index(
+        b"@r1 1:N:0:ACGT\n",
+        RevcompMode::I5,
+        true,
+        b"@r1 1:N:0:ACGT\n"
+    )]
+    fn rewrite_header_index_cases(
+        #[case] input: &[u8],
+        #[case] revcomp: RevcompMode,
+        #[case] single_index: bool,
+        #[case] expected: &[u8],
+    ) {
+        let mut header = input.to_vec();
+        rewrite_header_index(
+            &mut header,
+            Options {
+                revcomp,
+                single_index,
+            },
+        );
+        assert_eq!(header.as_slice(), expected);
+    }
+
+    #[test]
+    fn transform_handles_zero_length_record() {
+        // A 0bp read (e.g. fully adapter-trimmed) has an empty sequence
+        // line, so its quality line is also empty; make sure that blank
+        // quality line is still consumed and the next record parses cleanly.
+        let input: &[u8] = b"@r1 1:N:0:AAAA+ACTACTTGAG\n\n+\n\n@r2 1:N:0:CCCC+atcacg\nACGT\n+\n####\n";
+        let mut output = Vec::new();
+        let stats = transform(input, &mut output, Options::default()).unwrap();
+        assert_eq!(stats.records, 2);
+        assert_eq!(
+            output,
+            b"@r1 1:N:0:AAAA+CTCAAGTAGT\n\n+\n\n@r2 1:N:0:CCCC+cgtgat\nACGT\n+\n####\n"
+        );
+    }
+}
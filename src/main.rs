@@ -1,250 +1,239 @@
-use std::io::{self, Read, Write, BufRead};
-use memchr::{memchr,memrchr};
-
-/// Return the complement of a DNA base (A,C,G,T,N), preserving case.
-#[inline(always)]
-fn complement_base(b: u8) -> u8 {
-    // Handles A,C,G,T,N (upper/lower). Leaves other bytes unchanged.
-    match b {
-        b'A' => b'T',
-        b'C' => b'G',
-        b'G' => b'C',
-        b'T' => b'A',
-        b'N' => b'N',
-        b'a' => b't',
-        b'c' => b'g',
-        b'g' => b'c',
-        b't' => b'a',
-        b'n' => b'n',
-        _ => b,
-    }
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use bzip2::read::BzDecoder;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use xz2::read::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+use fastq_fix_i5::{transform_paired, transform_threaded, Options, RevcompMode};
+
+const READ_BUF_SIZE: usize = 64 * 1024;
+const WRITE_BUF_SIZE: usize = 64 * 1024;
+
+/// Output compression requested via `--compress`, or inferred from an
+/// output file's extension.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Compress {
+    None,
+    Gzip,
+    Zstd,
 }
 
-#[inline(always)]
-fn reverse_complement_in_place(buf: &mut [u8]) {
-    let mut i = 0;
-    let mut j = buf.len();
-
-    while i < j {
-        j -= 1;
-        let a = complement_base(buf[i]);
-        let b = complement_base(buf[j]);
-        buf[i] = b;
-        buf[j] = a;
-        i += 1;
+impl Compress {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "none" => Some(Compress::None),
+            "gzip" | "gz" => Some(Compress::Gzip),
+            "zstd" | "zst" => Some(Compress::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Infer a compression mode from an output path's extension, defaulting
+    /// to `None` when the extension isn't recognized.
+    fn infer_from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compress::Gzip,
+            Some("zst") | Some("zstd") => Compress::Zstd,
+            _ => Compress::None,
+        }
     }
 }
 
-/// Rewrite a FASTQ header line expected to end with "...:i7+i5\n" (or without final newline).
-/// We reverse-complement i5 only and write the modified header to `out`.
-/// Returns false if pattern not found (no rewrite); true if rewritten.
-fn rewrite_header_i5(header: &mut Vec<u8>) -> bool {
-    if header.is_empty() || header[0] != b'@' {
-        // Not a FASTQ header; pass through unchanged.
-        return false;
+#[derive(Default)]
+struct Cli {
+    in1: Option<PathBuf>,
+    in2: Option<PathBuf>,
+    out1: Option<PathBuf>,
+    out2: Option<PathBuf>,
+    compress: Option<Compress>,
+    revcomp: Option<RevcompMode>,
+    single_index: bool,
+    threads: usize,
+}
+
+impl Cli {
+    const DEFAULT_THREADS: usize = 1;
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.into())
+}
+
+fn parse_revcomp(s: &str) -> Option<RevcompMode> {
+    match s {
+        "none" => Some(RevcompMode::None),
+        "i7" => Some(RevcompMode::I7),
+        "i5" => Some(RevcompMode::I5),
+        "both" => Some(RevcompMode::Both),
+        _ => None,
     }
+}
 
-    // Check for trailing newline.
-    let has_nl = header.last() == Some(&b'\n');
+fn next_path(args: &mut impl Iterator<Item = String>, flag: &str) -> io::Result<PathBuf> {
+    args.next()
+        .map(PathBuf::from)
+        .ok_or_else(|| invalid(format!("{flag} requires a path")))
+}
 
-    // Find last ':' in the header.
-    let Some(j) = memrchr(b':', header) else {
-        return false;
+fn parse_args() -> io::Result<Cli> {
+    let mut cli = Cli {
+        threads: Cli::DEFAULT_THREADS,
+        ..Cli::default()
     };
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--compress" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| invalid("--compress requires a value"))?;
+                cli.compress = Some(Compress::parse(&value).ok_or_else(|| {
+                    invalid(format!(
+                        "unknown --compress mode '{value}' (expected gzip|zstd|none)"
+                    ))
+                })?);
+            }
+            "--in1" => cli.in1 = Some(next_path(&mut args, "--in1")?),
+            "--in2" => cli.in2 = Some(next_path(&mut args, "--in2")?),
+            "--out1" => cli.out1 = Some(next_path(&mut args, "--out1")?),
+            "--out2" => cli.out2 = Some(next_path(&mut args, "--out2")?),
+            "--revcomp" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| invalid("--revcomp requires a value"))?;
+                cli.revcomp = Some(parse_revcomp(&value).ok_or_else(|| {
+                    invalid(format!(
+                        "unknown --revcomp mode '{value}' (expected i5|i7|both|none)"
+                    ))
+                })?);
+            }
+            "--single-index" => cli.single_index = true,
+            "--threads" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| invalid("--threads requires a value"))?;
+                cli.threads = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&n| n >= 1)
+                    .ok_or_else(|| invalid(format!("invalid --threads value '{value}' (expected a positive integer)")))?;
+            }
+            other => return Err(invalid(format!("unrecognized argument '{other}'"))),
+        }
+    }
 
-    // Find '+' after that last ':'.
-    let Some(rel_k) = memchr(b'+', &header[j + 1..]) else {
-        return false;
-    };
+    if cli.in1.is_some() != cli.out1.is_some() {
+        return Err(invalid("--in1 and --out1 must be given together"));
+    }
+    if cli.in2.is_some() != cli.out2.is_some() {
+        return Err(invalid("--in2 and --out2 must be given together"));
+    }
+    if cli.in2.is_some() && cli.in1.is_none() {
+        return Err(invalid("--in2/--out2 require --in1/--out1 (paired mode fixes both mates)"));
+    }
+    if cli.in2.is_some() && cli.threads > 1 {
+        return Err(invalid(
+            "--threads is not supported with --in2 (paired mode reads both mates in lockstep)",
+        ));
+    }
 
-    // i5 header is everything after '+' excluding the newline if present
-    let i5_start = j + 1 + rel_k + 1;
-    let i5_end = if has_nl {
-        header.len() - 1
+    Ok(cli)
+}
+
+/// Peek at the first bytes of `input` and wrap it in the matching streaming
+/// decoder (gzip, zstd, bzip2, xz), falling back to the reader itself when
+/// no magic number matches.
+fn detect_input_decoder<R: BufRead + 'static>(mut input: R) -> io::Result<Box<dyn BufRead>> {
+    let magic = input.fill_buf()?.to_vec();
+
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(io::BufReader::new(MultiGzDecoder::new(input))))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Ok(Box::new(io::BufReader::new(ZstdDecoder::new(input)?)))
+    } else if magic.starts_with(b"BZh") {
+        Ok(Box::new(io::BufReader::new(BzDecoder::new(input))))
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Ok(Box::new(io::BufReader::new(XzDecoder::new(input))))
     } else {
-        header.len()
-    };
-    let i5 = &mut header[i5_start..i5_end];
-    reverse_complement_in_place(i5);
-    true
+        Ok(Box::new(input))
+    }
 }
 
-fn main() -> io::Result<()> {
-    let read_buf_size = 64*1024;  // 1 MiB buffer for I/O
-    let write_buf_size = 64*1024;  // 1 MiB buffer for I/O
-    let stdin = io::stdin();
-    let mut input = io::BufReader::with_capacity(read_buf_size, stdin.lock());
-    let stdout = io::stdout();
-    let mut output = io::BufWriter::with_capacity(write_buf_size, stdout.lock());
-
-    // Buffers for the 4 FASTQ record lines that constitute one read
-    let mut h = Vec::<u8>::with_capacity(256);
-    let mut s = Vec::<u8>::with_capacity(256);
-    let mut p = Vec::<u8>::with_capacity(256);
-    let mut q = Vec::<u8>::with_capacity(256);
-
-    loop {
-        h.clear();
-        let n = read_line(&mut input, &mut h)?;
-        if n == 0 {
-            break; // EOF
-        }
+/// Wrap `output` in the streaming encoder requested by `--compress` (or
+/// inferred from the output path's extension).
+fn wrap_output_encoder<W: Write + 'static>(output: W, compress: Compress) -> io::Result<Box<dyn Write>> {
+    match compress {
+        Compress::None => Ok(Box::new(output)),
+        Compress::Gzip => Ok(Box::new(GzEncoder::new(output, Compression::default()))),
+        Compress::Zstd => Ok(Box::new(ZstdEncoder::new(output, 0)?.auto_finish())),
+    }
+}
 
-        s.clear();
-        p.clear();
-        q.clear();
-
-        if read_line(&mut input, &mut s)? == 0
-            || read_line(&mut input, &mut p)? == 0
-            || read_line(&mut input, &mut q)? == 0
-        {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "truncated FASTQ record (expected 4 lines)",
-            ));
+/// Open an input stream: a file at `path` (auto-detecting compression from
+/// its content), or stdin when `path` is `None`.
+fn open_input(path: &Option<PathBuf>) -> io::Result<Box<dyn BufRead>> {
+    match path {
+        Some(path) => detect_input_decoder(io::BufReader::with_capacity(
+            READ_BUF_SIZE,
+            File::open(path)?,
+        )),
+        None => {
+            let stdin = io::stdin();
+            detect_input_decoder(io::BufReader::with_capacity(READ_BUF_SIZE, stdin.lock()))
         }
-
-        rewrite_header_i5(&mut h);
-        output.write_all(&h)?;
-        output.write_all(&s)?;
-        output.write_all(&p)?;
-        output.write_all(&q)?;
     }
-
-    output.flush()?;
-    Ok(())
 }
 
-/// Read one line (including the trailing '\n' if present) into buf.
-/// Returns number of bytes read (0 on EOF).
-#[inline(always)]
-fn read_line<R: Read>(r: &mut io::BufReader<R>, buf: &mut Vec<u8>) -> io::Result<usize> {
-    buf.clear();
-    let mut total = 0usize;
-    loop {
-        let available = r.fill_buf()?;
-        if available.is_empty() {
-            return Ok(total);
+/// Open an output stream: a file at `path` (compressed per `compress`, or
+/// inferred from the path's extension), or stdout when `path` is `None`.
+fn open_output(path: &Option<PathBuf>, compress: Option<Compress>) -> io::Result<Box<dyn Write>> {
+    match path {
+        Some(path) => {
+            let mode = compress.unwrap_or_else(|| Compress::infer_from_path(path));
+            wrap_output_encoder(
+                io::BufWriter::with_capacity(WRITE_BUF_SIZE, File::create(path)?),
+                mode,
+            )
         }
-        if let Some(pos) = memchr(b'\n', available) {
-            // include newline
-            buf.extend_from_slice(&available[..=pos]);
-            let consume = pos + 1;
-            r.consume(consume);
-            total += consume;
-            return Ok(total);
-        } else {
-            // consume all
-            buf.extend_from_slice(available);
-            let consume = available.len();
-            r.consume(consume);
-            total += consume;
+        None => {
+            let stdout = io::stdout();
+            wrap_output_encoder(
+                io::BufWriter::with_capacity(WRITE_BUF_SIZE, stdout.lock()),
+                compress.unwrap_or(Compress::None),
+            )
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rstest::rstest;
-
-    #[rstest]
-    #[case::actacttgag(
-        b"@VH00821:6:AACCCKLM5:1:1101:18231:1000 1:N:0:TCTTGAGGTT+ACTACTTGAG\n",
-        b"@VH00821:6:AACCCKLM5:1:1101:18231:1000 1:N:0:TCTTGAGGTT+CTCAAGTAGT\n"
-    )]
-    #[case::acgt(
-        b"@r1 1:N:0:AAAA+ACGT\n",
-        b"@r1 1:N:0:AAAA+ACGT\n"
-    )]
-    #[case::acgt_lowercase(
-        b"@r2 1:N:0:CCCC+acgt\n",
-        b"@r2 1:N:0:CCCC+acgt\n"
-    )]
-    #[case::nnnn(
-        b"@r3 1:N:0:GGGG+NNNN\n",
-        b"@r3 1:N:0:GGGG+NNNN\n"
-    )]
-    #[case::actg_mixedcase(
-        b"@r4 1:N:0:TTTT+AcTg\n",
-        b"@r4 1:N:0:TTTT+cAgT\n"
-    )]
-    #[case::extra_colons(
-        b"@inst:run:flow:lane:tile:x:y 1:N:0:AAAA+TTTT\n",
-        b"@inst:run:flow:lane:tile:x:y 1:N:0:AAAA+AAAA\n"
-    )]
-    #[case::no_plus(
-        b"@r5 1:N:0:AAAA\n",
-        b"@r5 1:N:0:AAAA\n"
-    )]
-    #[case::no_colon(
-        b"@r6 no_index_here\n",
-        b"@r6 no_index_here\n"
-    )]
-    #[case::no_newline(
-        b"@r7 1:N:0:CCCC+AGTC",
-        b"@r7 1:N:0:CCCC+GACT"
-    )]
-    #[case::empty_header(
-        b"@\n",
-        b"@\n"
-    )]
-    #[case::empty_i5(
-        b"@pyt1 1:N:0:AAAA+\n",
-        b"@pyt1 1:N:0:AAAA+\n"
-    )]
-    #[case::single_a(
-        b"@pyt2 1:N:0:AAAA+A\n",
-        b"@pyt2 1:N:0:AAAA+T\n"
-    )]
-    #[case::single_n(
-        b"@pyt3 1:N:0:AAAA+N\n",
-        b"@pyt3 1:N:0:AAAA+N\n"
-    )]
-    #[case::mixed_case_short(
-        b"@pyt4 1:N:0:AAAA+AaCg\n",
-        b"@pyt4 1:N:0:AAAA+cGtT\n"
-    )]
-    #[case::acgtn(
-        b"@pyt5 1:N:0:AAAA+AcgTN\n",
-        b"@pyt5 1:N:0:AAAA+NAcgT\n"
-    )]
-    #[case::all_as(
-        b"@pyt6 1:N:0:AAAA+AAAA\n",
-        b"@pyt6 1:N:0:AAAA+TTTT\n"
-    )]
-    #[case::all_cs(
-        b"@pyt7 1:N:0:AAAA+CCCC\n",
-        b"@pyt7 1:N:0:AAAA+GGGG\n"
-    )]
-    #[case::at_repeat(
-        b"@pyt8 1:N:0:AAAA+ATATAT\n",
-        b"@pyt8 1:N:0:AAAA+ATATAT\n"
-    )]
-    #[case::cg_repeat(
-        b"@pyt9 1:N:0:AAAA+CGCGCG\n",
-        b"@pyt9 1:N:0:AAAA+CGCGCG\n"
-    )]
-    #[case::ns_flanking(
-        b"@pyt10 1:N:0:AAAA+NNACGTNN\n",
-        b"@pyt10 1:N:0:AAAA+NNACGTNN\n"
-    )]
-    #[case::general_atcacg(
-        b"@pyt11 1:N:0:AAAA+ATCACG\n",
-        b"@pyt11 1:N:0:AAAA+CGTGAT\n"
-    )]
-    #[case::general_ttaggc(
-        b"@pyt12 1:N:0:AAAA+TTAGGC\n",
-        b"@pyt12 1:N:0:AAAA+GCCTAA\n"
-    )]
-    fn rewrite_header_i5_cases(#[case] input: &[u8], #[case] expected: &[u8]) {
-        let mut header = input.to_vec();
-        rewrite_header_i5(&mut header);
-        assert_eq!(
-            header.as_slice(),
-            expected,
-            "input = {:?}",
-            std::str::from_utf8(input).unwrap_or("<non-utf8>")
-        );
+fn main() -> io::Result<()> {
+    let cli = parse_args()?;
+    let opts = Options {
+        revcomp: cli.revcomp.unwrap_or(Options::default().revcomp),
+        single_index: cli.single_index,
+    };
+
+    let input1 = open_input(&cli.in1)?;
+    let mut output1 = open_output(&cli.out1, cli.compress)?;
+
+    if cli.in2.is_some() {
+        // Paired mode reads both mates in lockstep to catch a record-count
+        // mismatch as early as possible; parse_args rejects --threads > 1
+        // together with --in2 since transform_paired doesn't use it.
+        let input2 = open_input(&cli.in2)?;
+        let mut output2 = open_output(&cli.out2, cli.compress)?;
+        transform_paired(input1, input2, &mut output1, &mut output2, opts)?;
+        output2.flush()?;
+    } else {
+        transform_threaded(input1, &mut output1, opts, cli.threads)?;
     }
+
+    output1.flush()
 }
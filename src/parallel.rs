@@ -0,0 +1,137 @@
+//! Multi-threaded variant of [`crate::transform`]: records are parsed in
+//! fixed-size batches and handed to a pool of worker threads that rewrite
+//! each batch's header(s); batches are then written out in their original
+//! order so record order (critical for downstream paired-end correctness)
+//! is preserved even though the rewrite work ran out of order.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{read_record, rewrite_header_index, write_record, Options, Record, Stats};
+
+/// Number of records handed to a worker thread per unit of work.
+const BATCH_SIZE: usize = 4096;
+
+/// Like [`crate::transform`], but spreads the header-rewrite work across
+/// `threads` worker threads. `threads <= 1` takes the exact single-threaded
+/// streaming path used by [`crate::transform`].
+pub fn transform_threaded<R: Read, W: Write>(
+    reader: R,
+    mut writer: W,
+    opts: Options,
+    threads: usize,
+) -> io::Result<Stats> {
+    if threads <= 1 {
+        return crate::transform(reader, writer, opts);
+    }
+
+    let mut input = io::BufReader::new(reader);
+    let mut stats = Stats::default();
+
+    // Bounded so the channel can only ever hold a few batches' worth of
+    // records: once it's full, sending blocks until a worker drains one,
+    // which applies backpressure to reading instead of buffering the whole
+    // input in memory before any output is written.
+    let (batch_tx, batch_rx) = mpsc::sync_channel::<(u64, Vec<Record>)>(2 * threads);
+    let (result_tx, result_rx) = mpsc::channel::<(u64, Vec<Record>, u64)>();
+    let batch_rx = Mutex::new(batch_rx);
+
+    thread::scope(|scope| -> io::Result<()> {
+        for _ in 0..threads {
+            let batch_rx = &batch_rx;
+            let result_tx = result_tx.clone();
+            scope.spawn(move || loop {
+                let Ok((id, mut batch)) = batch_rx.lock().unwrap().recv() else {
+                    break;
+                };
+                let mut rewritten = 0u64;
+                for rec in &mut batch {
+                    if rewrite_header_index(&mut rec.header, opts) {
+                        rewritten += 1;
+                    }
+                }
+                if result_tx.send((id, batch, rewritten)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_tx);
+
+        // Buffers worker results that finished out of order until the next
+        // batch due for writing (`next_write`) is among them.
+        let mut pending: HashMap<u64, (Vec<Record>, u64)> = HashMap::new();
+        let mut next_write = 0u64;
+
+        // Write out every already-finished batch that's next in line,
+        // without blocking; called after every send below so the writer
+        // stays caught up with the workers instead of falling behind until
+        // reading finishes.
+        macro_rules! drain_ready {
+            () => {
+                while let Some((batch, rewritten)) = pending.remove(&next_write) {
+                    for rec in &batch {
+                        write_record(&mut writer, rec)?;
+                    }
+                    stats.records += batch.len() as u64;
+                    stats.headers_rewritten += rewritten;
+                    next_write += 1;
+                }
+            };
+        }
+
+        // Scan the input for record boundaries and hand off fixed-size
+        // batches to the worker pool, interleaving with draining finished
+        // results so reading and writing happen concurrently rather than as
+        // two sequential passes over the whole file. Reading happens on this
+        // thread since stdin/stdout locks aren't `Send`, but the CPU-bound
+        // rewrite work still runs fully in parallel across the worker
+        // threads above.
+        let mut next_id = 0u64;
+        loop {
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            while batch.len() < BATCH_SIZE {
+                let mut rec = Record::default();
+                if !read_record(&mut input, &mut rec)? {
+                    break;
+                }
+                batch.push(rec);
+            }
+            let is_last = batch.len() < BATCH_SIZE;
+            if !batch.is_empty() {
+                while let Ok((id, batch, rewritten)) = result_rx.try_recv() {
+                    pending.insert(id, (batch, rewritten));
+                }
+                drain_ready!();
+
+                if batch_tx.send((next_id, batch)).is_err() {
+                    break;
+                }
+                next_id += 1;
+            }
+            if is_last {
+                break;
+            }
+        }
+        let total_batches = next_id;
+        drop(batch_tx);
+
+        // Input is exhausted; block on the remaining in-flight results.
+        while next_write < total_batches {
+            drain_ready!();
+            if next_write >= total_batches {
+                break;
+            }
+            let (id, batch, rewritten) = result_rx
+                .recv()
+                .map_err(|_| io::Error::other("worker pool terminated unexpectedly"))?;
+            pending.insert(id, (batch, rewritten));
+        }
+
+        Ok(())
+    })?;
+
+    Ok(stats)
+}
@@ -57,18 +57,18 @@ ACGT\n\
 }
 
 #[test]
-fn invalid_missing_plus() {
-    // Missing '+' after the last ':' in the header
+fn missing_plus_passes_through_unchanged() {
+    // A header with no '+' (no dual-index field to rewrite) is passed
+    // through as-is rather than treated as an error; see the `no_plus` and
+    // `no_colon` cases in rewrite_header_i5_cases for the library-level
+    // behavior this exercises at the CLI.
     let input = b"@r1 1:N:0:AAAAACGT\n\
 ACGT\n\
 +\n\
 !!!!\n";
 
     let mut cmd = cargo_bin_cmd!("fastq-fix-i5");
-    cmd.write_stdin(input)
-        .assert()
-        .failure()
-        .stderr(predicates::str::contains("'+'"));
+    cmd.write_stdin(input).assert().success().stdout(&input[..]);
 }
 
 #[test]
@@ -83,3 +83,143 @@ ACGT\n\
         .failure()
         .stderr(predicates::str::contains("truncated"));
 }
+
+#[test]
+fn compress_gzip_roundtrip() {
+    let input = b"@r1 1:N:0:AAAA+ACTACTTGAG\n\
+ACGT\n\
++\n\
+!!!!\n\
+@r2 1:N:0:CCCC+atcacg\n\
+TGCA\n\
++\n\
+####\n";
+
+    // Compress the rewritten output on the way out.
+    let compressed = cargo_bin_cmd!("fastq-fix-i5")
+        .arg("--compress")
+        .arg("gzip")
+        .write_stdin(&input[..])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert!(compressed.starts_with(&[0x1f, 0x8b]), "output isn't gzip");
+
+    // Piping the gzip output back in: it's auto-detected and decompressed,
+    // then rewritten again, recovering the original input (same property
+    // `valid_two_records` checks for the uncompressed case).
+    cargo_bin_cmd!("fastq-fix-i5")
+        .write_stdin(compressed)
+        .assert()
+        .success()
+        .stdout(&input[..]);
+}
+
+#[test]
+fn paired_mode_rewrites_both_mates() {
+    let dir = tempfile::tempdir().unwrap();
+    let in1 = dir.path().join("r1.fastq");
+    let in2 = dir.path().join("r2.fastq");
+    let out1 = dir.path().join("out1.fastq");
+    let out2 = dir.path().join("out2.fastq");
+
+    std::fs::write(&in1, b"@r1 1:N:0:AAAA+ACTACTTGAG\nACGT\n+\n!!!!\n").unwrap();
+    std::fs::write(&in2, b"@r1 2:N:0:AAAA+ACTACTTGAG\nTGCA\n+\n####\n").unwrap();
+
+    cargo_bin_cmd!("fastq-fix-i5")
+        .arg("--in1")
+        .arg(&in1)
+        .arg("--in2")
+        .arg(&in2)
+        .arg("--out1")
+        .arg(&out1)
+        .arg("--out2")
+        .arg(&out2)
+        .assert()
+        .success();
+
+    assert_eq!(
+        std::fs::read(&out1).unwrap(),
+        b"@r1 1:N:0:AAAA+CTCAAGTAGT\nACGT\n+\n!!!!\n"
+    );
+    assert_eq!(
+        std::fs::read(&out2).unwrap(),
+        b"@r1 2:N:0:AAAA+CTCAAGTAGT\nTGCA\n+\n####\n"
+    );
+}
+
+#[test]
+fn paired_mode_mismatched_record_count_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let in1 = dir.path().join("r1.fastq");
+    let in2 = dir.path().join("r2.fastq");
+    let out1 = dir.path().join("out1.fastq");
+    let out2 = dir.path().join("out2.fastq");
+
+    std::fs::write(
+        &in1,
+        b"@r1 1:N:0:AAAA+ACTACTTGAG\nACGT\n+\n!!!!\n\
+@r2 1:N:0:AAAA+ACTACTTGAG\nACGT\n+\n!!!!\n",
+    )
+    .unwrap();
+    std::fs::write(&in2, b"@r1 2:N:0:AAAA+ACTACTTGAG\nTGCA\n+\n####\n").unwrap();
+
+    cargo_bin_cmd!("fastq-fix-i5")
+        .arg("--in1")
+        .arg(&in1)
+        .arg("--in2")
+        .arg(&in2)
+        .arg("--out1")
+        .arg(&out1)
+        .arg("--out2")
+        .arg(&out2)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("mismatched record count"));
+}
+
+#[test]
+fn threads_matches_single_threaded_output() {
+    let record = b"@r1 1:N:0:AAAA+ACTACTTGAG\nACGT\n+\n!!!!\n";
+    let input = record.repeat(20_000);
+
+    let single = cargo_bin_cmd!("fastq-fix-i5")
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let threaded = cargo_bin_cmd!("fastq-fix-i5")
+        .arg("--threads")
+        .arg("4")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    assert_eq!(single, threaded, "output must match regardless of --threads");
+}
+
+#[test]
+fn threads_rejected_with_paired_mode() {
+    let mut cmd = cargo_bin_cmd!("fastq-fix-i5");
+    cmd.arg("--in1")
+        .arg("r1.fastq")
+        .arg("--in2")
+        .arg("r2.fastq")
+        .arg("--out1")
+        .arg("o1.fastq")
+        .arg("--out2")
+        .arg("o2.fastq")
+        .arg("--threads")
+        .arg("4")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--threads"));
+}